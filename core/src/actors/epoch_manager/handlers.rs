@@ -0,0 +1,25 @@
+//! Handlers for messages sent to `EpochManager`.
+
+use actix::{Context, Handler, Message};
+
+use super::{EpochManager, SendableNotification};
+
+/// Subscribe an actor to be notified on a periodic cadence of epochs (e.g. every Nth epoch),
+/// without needing to re-subscribe after each notification.
+///
+/// Unlike `SubscribeEpoch`/`SubscribeAll` (`crate::actors::messages`), this message is declared
+/// here, next to its handler, rather than in the shared messages module, which this change does
+/// not otherwise touch.
+pub struct SubscribePeriodic(pub Box<dyn SendableNotification>);
+
+impl Message for SubscribePeriodic {
+    type Result = ();
+}
+
+impl Handler<SubscribePeriodic> for EpochManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscribePeriodic, _ctx: &mut Context<Self>) -> Self::Result {
+        self.subscribe_periodic(msg.0);
+    }
+}
@@ -4,7 +4,11 @@ use ansi_term::Color::Purple;
 
 use log::{debug, error, info, warn};
 
-use std::{collections::BTreeMap, time::Duration};
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use witnet_config::config::Config;
 use witnet_data_structures::chain::Epoch;
@@ -15,6 +19,8 @@ use crate::actors::messages::{EpochNotification, EpochResult};
 mod actor;
 mod handlers;
 
+pub use self::handlers::SubscribePeriodic;
+
 /// Possible errors when getting the current epoch
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum EpochManagerError {
@@ -31,6 +37,16 @@ pub enum EpochManagerError {
     Overflow,
 }
 
+/// Number of failed epoch notification deliveries. A single-epoch subscription's notification
+/// is counted once, on its first failed attempt, however often it is retried; all-epoch and
+/// periodic subscriptions count again on every failed attempt, including retries
+static FAILED_EPOCH_NOTIFICATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Snapshot of the failed-epoch-notification counter, exposed for metrics/diagnostics
+pub fn failed_epoch_notifications() -> u64 {
+    FAILED_EPOCH_NOTIFICATIONS.load(Ordering::Relaxed)
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////
 // ACTOR BASIC STRUCTURE
 ////////////////////////////////////////////////////////////////////////////////////////
@@ -49,8 +65,17 @@ pub struct EpochManager {
     /// Subscriptions to all epochs
     subscriptions_all: Vec<Box<dyn SendableNotification>>,
 
+    /// Subscriptions to a periodic cadence of epochs (every Nth epoch)
+    subscriptions_periodic: Vec<Box<dyn SendableNotification>>,
+
     /// Last epoch that was checked by the epoch monitor process
     last_checked_epoch: Option<Epoch>,
+
+    /// Single-epoch subscriptions whose delivery failed on a previous checkpoint monitor tick
+    /// and still have retries left. Tracked independently of `subscriptions_epoch` (which is
+    /// only scanned for the range `last_checked_epoch..=current_epoch`) so a retry is not
+    /// silently dropped once `last_checked_epoch` advances past the epoch it was keyed under
+    pending_epoch_retries: Vec<(Epoch, Box<dyn SendableNotification>)>,
 }
 
 /// Required trait for being able to retrieve EpochManager address from system registry
@@ -73,6 +98,12 @@ impl EpochManager {
         }
         self.checkpoints_period = Some(period);
     }
+    /// Subscribe an actor to be notified on a periodic cadence of epochs, e.g. every 10th
+    /// checkpoint, without having to re-subscribe after each notification. Reachable from other
+    /// actors via the `SubscribePeriodic` message and its handler in `handlers.rs`
+    pub fn subscribe_periodic(&mut self, subscription: Box<dyn SendableNotification>) {
+        self.subscriptions_periodic.push(subscription);
+    }
     /// Calculate the last checkpoint (current epoch) at the supplied timestamp
     pub fn epoch_at(&self, timestamp: i64) -> EpochResult<Epoch> {
         match (self.checkpoint_zero_timestamp, self.checkpoints_period) {
@@ -164,6 +195,13 @@ impl EpochManager {
                     subscription.send_notification(current_epoch);
                 }
 
+                // Send message to actors which subscribed to a periodic cadence of epochs.
+                // Each subscription tracks its own next-fire epoch, so this is safe to call
+                // on every tick regardless of the cadence
+                for subscription in &mut act.subscriptions_periodic {
+                    subscription.send_notification(current_epoch);
+                }
+
                 // Get all the checkpoints that had some subscription but were skipped for some
                 // reason (process sent to background, checkpoint monitor process had no
                 // resources to execute in time...)
@@ -173,21 +211,23 @@ impl EpochManager {
                     .map(|(k, _v)| *k)
                     .collect();
 
+                // Pull out the subscriptions due this tick, newly-skipped or not
+                let due: Vec<_> = epoch_checkpoints
+                    .into_iter()
+                    .filter_map(|checkpoint| {
+                        act.subscriptions_epoch
+                            .remove(&checkpoint)
+                            .map(|subscriptions| (checkpoint, subscriptions))
+                    })
+                    .collect();
+
                 // Send notifications for skipped checkpoints for subscriptions to a particular
-                // epoch
-                // Notifications for skipped checkpoints are not sent for subscriptions to all
-                // epochs
-                for checkpoint in epoch_checkpoints {
-                    // Get the subscriptions to the skipped checkpoint
-                    if let Some(subscriptions) = act.subscriptions_epoch.remove(&checkpoint) {
-                        // Send notifications to subscribers for skipped checkpoints
-                        for mut subscription in subscriptions {
-                            // TODO: should send messages or just drop?
-                            // TODO: send notifications also for subscriptions to all epochs?
-                            subscription.send_notification(checkpoint);
-                        }
-                    }
-                }
+                // epoch, together with any notification still pending from a previous tick's
+                // failed attempt. Subscriptions to all epochs handle their own catch-up above,
+                // based on each subscription's own last_notified_epoch
+                let pending_retries = std::mem::take(&mut act.pending_epoch_retries);
+                act.pending_epoch_retries =
+                    retry_epoch_subscriptions(due, pending_retries);
 
                 // Update last checked epoch
                 act.last_checked_epoch = Some(current_epoch);
@@ -206,10 +246,49 @@ impl EpochManager {
     }
 }
 
+/// Number of delivery attempts, the first one included, made for a single-epoch notification
+/// on consecutive checkpoint monitor ticks before it is dropped for good
+const MAX_NOTIFICATION_RETRIES: u8 = 3;
+
+/// Decide whether a failed delivery attempt should be counted in `FAILED_EPOCH_NOTIFICATIONS`.
+/// Only the first attempt for a given notification counts, so that one notification which is
+/// attempted up to `MAX_NOTIFICATION_RETRIES` times still only increments the counter once
+fn is_first_failed_attempt(retries_left: u8) -> bool {
+    retries_left == MAX_NOTIFICATION_RETRIES
+}
+
+/// Attempt delivery for every single-epoch subscription due this tick (`due`, keyed by the
+/// epoch each subscription was waiting for) together with every subscription still pending
+/// from a previous tick's failed attempt (`pending_retries`). Returns whichever of those are
+/// still undelivered afterwards, to be retried again on the next tick regardless of how far
+/// `last_checked_epoch` advances in the meantime. Kept free of any actix dependency so it can
+/// be unit tested with a plain `SendableNotification` mock
+fn retry_epoch_subscriptions(
+    due: Vec<(Epoch, Vec<Box<dyn SendableNotification>>)>,
+    pending_retries: Vec<(Epoch, Box<dyn SendableNotification>)>,
+) -> Vec<(Epoch, Box<dyn SendableNotification>)> {
+    due.into_iter()
+        .flat_map(|(epoch, subscriptions)| {
+            subscriptions
+                .into_iter()
+                .map(move |subscription| (epoch, subscription))
+        })
+        .chain(pending_retries)
+        .filter_map(|(epoch, mut subscription)| {
+            if subscription.send_notification(epoch) {
+                None
+            } else {
+                Some((epoch, subscription))
+            }
+        })
+        .collect()
+}
+
 /// Trait that must follow all notifications that will be sent back to subscriber actors
 pub trait SendableNotification: Send {
-    /// Send notification back to the subscriber
-    fn send_notification(&mut self, current_epoch: Epoch);
+    /// Send notification back to the subscriber. Returns `false` if delivery failed and
+    /// should be retried on the next checkpoint monitor tick
+    fn send_notification(&mut self, current_epoch: Epoch) -> bool;
 }
 
 /// Notification for a particular epoch: instantiated by each actor that subscribes to a particular
@@ -220,35 +299,144 @@ pub struct SingleEpochSubscription<T: Send> {
 
     /// Payload to be sent back to the subscriber actor
     pub payload: Option<T>,
+
+    /// Remaining delivery attempts before this notification is dropped for good
+    pub retries_left: u8,
+}
+
+impl<T: Send> SingleEpochSubscription<T> {
+    /// Create a new single-epoch subscription, with the default number of delivery retries
+    pub fn new(recipient: Recipient<EpochNotification<T>>, payload: T) -> Self {
+        SingleEpochSubscription {
+            recipient,
+            payload: Some(payload),
+            retries_left: MAX_NOTIFICATION_RETRIES,
+        }
+    }
 }
 
 /// Implementation of the SendableNotification trait for the SingleEpochSubscription
 impl<T: Send> SendableNotification for SingleEpochSubscription<T> {
     /// Function to send notification back to the subscriber
-    fn send_notification(&mut self, epoch: Epoch) {
+    fn send_notification(&mut self, epoch: Epoch) -> bool {
         // Get the payload from the notification
-        if let Some(payload) = self.payload.take() {
-            // Build an EpochNotification message to send back to the subscriber
-            let msg = EpochNotification {
-                checkpoint: epoch,
-                payload,
-            };
-
-            // Send EpochNotification message back to the subscriber
-            // TODO: ignore failure?
-            match self.recipient.do_send(msg) {
-                Ok(()) => {}
-                Err(_e) => {}
-            };
-        } else {
-            error!(
-                "No payload to be sent back to the subscribed actor for epoch {:?}",
-                epoch
-            );
+        let payload = match self.payload.take() {
+            Some(payload) => payload,
+            // Already delivered (or given up on) in a previous call
+            None => return true,
+        };
+
+        // Build an EpochNotification message to send back to the subscriber
+        let msg = EpochNotification {
+            checkpoint: epoch,
+            payload,
+        };
+
+        // Send EpochNotification message back to the subscriber
+        match self.recipient.do_send(msg) {
+            Ok(()) => true,
+            Err(e) => {
+                if is_first_failed_attempt(self.retries_left) {
+                    FAILED_EPOCH_NOTIFICATIONS.fetch_add(1, Ordering::Relaxed);
+                }
+                self.retries_left = self.retries_left.saturating_sub(1);
+
+                if self.retries_left == 0 {
+                    error!(
+                        "Giving up on epoch notification for #{} after exhausting retries: {}",
+                        epoch, e
+                    );
+                    true
+                } else {
+                    warn!(
+                        "Failed to deliver epoch notification for #{}, will retry \
+                         ({} attempts left): {}",
+                        epoch, self.retries_left, e
+                    );
+                    self.payload = Some(e.into_inner().payload);
+                    false
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod retry_epoch_subscriptions_tests {
+    use super::{retry_epoch_subscriptions, Epoch, SendableNotification};
+
+    /// A `SendableNotification` that fails its first `fail_times` deliveries, then succeeds
+    struct FlakyNotification {
+        fail_times: u8,
+        delivered_at: Vec<Epoch>,
+    }
+
+    impl SendableNotification for FlakyNotification {
+        fn send_notification(&mut self, current_epoch: Epoch) -> bool {
+            if self.fail_times > 0 {
+                self.fail_times -= 1;
+                return false;
+            }
+            self.delivered_at.push(current_epoch);
+            true
+        }
+    }
+
+    #[test]
+    fn a_failed_catchup_notification_is_retried_on_the_next_tick_and_eventually_delivered() {
+        // Simulates the motivating scenario from chunk0-2: the monitor wakes up late, finds a
+        // subscription for a long-past epoch, and fails to deliver it on the first attempt
+        let flaky: Box<dyn SendableNotification> = Box::new(FlakyNotification {
+            fail_times: 1,
+            delivered_at: Vec::new(),
+        });
+        let due = vec![(5, vec![flaky])];
+
+        // Tick 1: due this tick, fails, must be carried over as a pending retry
+        let still_pending = retry_epoch_subscriptions(due, Vec::new());
+        assert_eq!(still_pending.len(), 1);
+        assert_eq!(still_pending[0].0, 5);
+
+        // Tick 2: far past epoch 5 now, but the pending retry must still be re-examined and
+        // delivered for its *original* epoch, regardless of how far last_checked_epoch moved
+        let still_pending = retry_epoch_subscriptions(Vec::new(), still_pending);
+        assert!(still_pending.is_empty());
+    }
+
+    #[test]
+    fn a_delivered_notification_is_not_retried_again() {
+        let delivered: Box<dyn SendableNotification> = Box::new(FlakyNotification {
+            fail_times: 0,
+            delivered_at: Vec::new(),
+        });
+        let due = vec![(5, vec![delivered])];
+
+        let still_pending = retry_epoch_subscriptions(due, Vec::new());
+        assert!(still_pending.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod single_epoch_subscription_tests {
+    use super::{is_first_failed_attempt, MAX_NOTIFICATION_RETRIES};
+
+    #[test]
+    fn first_attempt_counts_as_a_failure() {
+        assert!(is_first_failed_attempt(MAX_NOTIFICATION_RETRIES));
+    }
+
+    #[test]
+    fn subsequent_retries_of_the_same_notification_do_not_recount() {
+        for retries_left in 0..MAX_NOTIFICATION_RETRIES {
+            assert!(!is_first_failed_attempt(retries_left));
         }
     }
 }
 
+/// Default cap on the number of individually-replayed epochs after a stalled monitor tick,
+/// for subscriptions that do not configure their own `max_catchup_epochs`
+pub const DEFAULT_MAX_CATCHUP_EPOCHS: Epoch = 10;
+
 /// Notification for all epochs: instantiated by each actor that subscribes to all epochs. Stored in
 /// the SubscribeAll struct and in the EpochManager as SendableNotification. Requires T to be
 /// cloned as this notification is to be sent many times
@@ -258,26 +446,334 @@ pub struct AllEpochSubscription<T: Clone + Send> {
 
     /// Payload to be sent back to the subscriber actor
     pub payload: T,
+
+    /// Last epoch for which a notification was actually delivered to this subscriber
+    pub last_notified_epoch: Option<Epoch>,
+
+    /// Maximum number of missed epochs to individually replay after a stalled monitor tick;
+    /// beyond this the whole gap collapses into a single notification for `current_epoch`
+    /// instead of flooding the subscriber with a long backlog of replayed epochs.
+    ///
+    /// NOTE: the collapsed notification is a plain `EpochNotification` for `current_epoch`,
+    /// indistinguishable on the wire from a normal on-time notification. `EpochNotification`
+    /// (`crate::actors::messages`) has no field to carry the gap size, so subscribers cannot
+    /// yet tell "epoch advanced normally" apart from "epoch jumped, please resync" without
+    /// independently tracking their own last-seen epoch and diffing it against this one.
+    /// TODO: extend `EpochNotification` with an explicit resync indicator once that message
+    /// type (shared by every subscription kind) can be revised across all its consumers.
+    pub max_catchup_epochs: Epoch,
+}
+
+impl<T: Clone + Send> AllEpochSubscription<T> {
+    /// Create a new all-epochs subscription, replaying up to `max_catchup_epochs` missed
+    /// epochs individually after a stalled monitor tick before collapsing the gap
+    pub fn new(
+        recipient: Recipient<EpochNotification<T>>,
+        payload: T,
+        max_catchup_epochs: Epoch,
+    ) -> Self {
+        AllEpochSubscription {
+            recipient,
+            payload,
+            last_notified_epoch: None,
+            max_catchup_epochs,
+        }
+    }
+
+    /// Send a single notification for `epoch` back to the subscriber. Returns `false` if
+    /// delivery failed, logging and counting the failure
+    fn notify(&self, epoch: Epoch) -> bool {
+        let msg = EpochNotification {
+            checkpoint: epoch,
+            payload: self.payload.clone(),
+        };
+
+        match self.recipient.do_send(msg) {
+            Ok(()) => true,
+            Err(e) => {
+                FAILED_EPOCH_NOTIFICATIONS.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Failed to deliver epoch notification for #{}, will retry on the next \
+                     checkpoint tick: {}",
+                    epoch, e
+                );
+                false
+            }
+        }
+    }
+}
+
+/// Catch-up decision for an `AllEpochSubscription`, as computed by `plan_catchup`
+#[derive(Debug, Eq, PartialEq)]
+enum Catchup {
+    /// Already delivered up to (or past) `current_epoch`, nothing to send
+    UpToDate,
+    /// Missed epochs are few enough to replay individually, one notification each
+    Replay(std::ops::RangeInclusive<Epoch>),
+    /// Missed epochs exceed `max_catchup_epochs`; collapse the gap into a single notification
+    /// for `current_epoch`. `gap` is the number of epochs skipped, for logging only
+    Collapsed { gap: Epoch },
+}
+
+/// Decide how an `AllEpochSubscription` should catch up to `current_epoch`, given the last
+/// epoch it was actually notified of. Pure and side-effect free so it can be unit tested
+/// without a real `Recipient`
+fn plan_catchup(
+    last_notified_epoch: Option<Epoch>,
+    current_epoch: Epoch,
+    max_catchup_epochs: Epoch,
+) -> Catchup {
+    let first_missing = last_notified_epoch.map_or(current_epoch, |e| e + 1);
+
+    if first_missing > current_epoch {
+        return Catchup::UpToDate;
+    }
+
+    let gap = current_epoch - first_missing;
+    // Number of epochs that would actually be replayed individually, were we not to collapse
+    let missed_epochs = gap + 1;
+    if missed_epochs > max_catchup_epochs {
+        Catchup::Collapsed { gap }
+    } else {
+        Catchup::Replay(first_missing..=current_epoch)
+    }
 }
 
 /// Implementation of the SendableNotification trait for the AllEpochSubscription
 impl<T: Clone + Send> SendableNotification for AllEpochSubscription<T> {
-    /// Function to send notification back to the subscriber
-    fn send_notification(&mut self, epoch: Epoch) {
-        // Clone the payload to be sent to the subscriber
-        let payload = self.payload.clone();
+    /// Send one notification per epoch missed since the last delivered one (catch-up), up to
+    /// `max_catchup_epochs`; beyond that, collapse the whole gap into a single notification
+    /// for `current_epoch`. Stops at the first delivery failure, so the failed epoch (and
+    /// any after it) is retried on the next checkpoint tick instead of being skipped
+    fn send_notification(&mut self, current_epoch: Epoch) -> bool {
+        match plan_catchup(self.last_notified_epoch, current_epoch, self.max_catchup_epochs) {
+            Catchup::UpToDate => true,
+            Catchup::Collapsed { gap } => {
+                warn!(
+                    "All-epoch subscriber missed {} epochs since #{}, collapsing catch-up into a \
+                     single notification for #{}",
+                    gap + 1,
+                    current_epoch - gap,
+                    current_epoch
+                );
 
-        // Build an EpochNotification message to send back to the subscriber
+                if !self.notify(current_epoch) {
+                    return false;
+                }
+                self.last_notified_epoch = Some(current_epoch);
+                true
+            }
+            Catchup::Replay(epochs) => {
+                for epoch in epochs {
+                    if !self.notify(epoch) {
+                        return false;
+                    }
+                    self.last_notified_epoch = Some(epoch);
+                }
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod all_epoch_subscription_tests {
+    use super::{plan_catchup, Catchup};
+
+    #[test]
+    fn no_prior_notification_replays_from_current_epoch() {
+        assert_eq!(plan_catchup(None, 5, 10), Catchup::Replay(5..=5));
+    }
+
+    #[test]
+    fn already_up_to_date_has_nothing_to_send() {
+        assert_eq!(plan_catchup(Some(5), 5, 10), Catchup::UpToDate);
+    }
+
+    #[test]
+    fn small_gap_is_replayed_one_epoch_at_a_time() {
+        assert_eq!(plan_catchup(Some(5), 8, 10), Catchup::Replay(6..=8));
+    }
+
+    #[test]
+    fn gap_beyond_max_catchup_epochs_collapses() {
+        assert_eq!(plan_catchup(Some(5), 20, 10), Catchup::Collapsed { gap: 14 });
+    }
+
+    #[test]
+    fn replaying_exactly_max_catchup_epochs_does_not_collapse() {
+        // last_notified_epoch = 0, current_epoch = 9 replays epochs 1..=9: exactly 9 epochs,
+        // matching max_catchup_epochs, so it must not collapse
+        assert_eq!(plan_catchup(Some(0), 9, 9), Catchup::Replay(1..=9));
+    }
+
+    #[test]
+    fn replaying_one_more_than_max_catchup_epochs_collapses() {
+        // last_notified_epoch = 0, current_epoch = 10 would replay 10 epochs, one more than
+        // max_catchup_epochs allows, so it must collapse instead
+        assert_eq!(plan_catchup(Some(0), 10, 9), Catchup::Collapsed { gap: 9 });
+    }
+}
+
+/// Notification for a periodic cadence of epochs: instantiated by each actor that subscribes to
+/// be notified every `period` epochs, optionally starting at a given epoch. Stored in the
+/// SubscribePeriodic message and in the EpochManager as a SendableNotification. Requires T to be
+/// cloned as this notification is to be sent many times
+pub struct PeriodicEpochSubscription<T: Clone + Send> {
+    /// Actor recipient, required to send a message back to the subscriber actor
+    pub recipient: Recipient<EpochNotification<T>>,
+
+    /// Payload to be sent back to the subscriber actor
+    pub payload: T,
+
+    /// Number of epochs between two consecutive notifications
+    pub period: Epoch,
+
+    /// Next epoch at (or after) which a notification is due
+    pub next_fire: Epoch,
+
+    /// When `true`, a monitor tick that crosses several missed period boundaries at once
+    /// collapses them into a single notification for the earliest missed boundary (the current
+    /// `next_fire`), instead of replaying every missed one
+    pub coalesce_catchup: bool,
+}
+
+impl<T: Clone + Send> PeriodicEpochSubscription<T> {
+    /// Create a new periodic subscription that fires every `period` epochs, optionally
+    /// starting at `first_epoch` (defaults to the first period boundary, i.e. `period`)
+    pub fn new(
+        recipient: Recipient<EpochNotification<T>>,
+        payload: T,
+        mut period: Epoch,
+        first_epoch: Option<Epoch>,
+        coalesce_catchup: bool,
+    ) -> Self {
+        if period == 0 {
+            warn!("Setting the periodic epoch subscription period to the minimum value of 1 epoch");
+            period = 1;
+        }
+
+        PeriodicEpochSubscription {
+            recipient,
+            payload,
+            period,
+            next_fire: first_epoch.unwrap_or(period),
+            coalesce_catchup,
+        }
+    }
+
+    /// Send a single notification for `epoch` back to the subscriber. Returns `false` if
+    /// delivery failed, logging and counting the failure
+    fn notify(&self, epoch: Epoch) -> bool {
         let msg = EpochNotification {
             checkpoint: epoch,
-            payload,
+            payload: self.payload.clone(),
         };
 
-        // Send EpochNotification message back to the subscriber
-        // TODO: ignore failure?
         match self.recipient.do_send(msg) {
-            Ok(()) => {}
-            Err(_e) => {}
+            Ok(()) => true,
+            Err(e) => {
+                FAILED_EPOCH_NOTIFICATIONS.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Failed to deliver epoch notification for #{}, will retry on the next \
+                     checkpoint tick: {}",
+                    epoch, e
+                );
+                false
+            }
+        }
+    }
+}
+
+/// Pure decision logic for `PeriodicEpochSubscription::send_notification`: which epoch(s) are
+/// due to fire, given the next-fire boundary, the current epoch and the cadence. Does not
+/// mutate any state, so it can be exercised without a real subscriber `Recipient`
+fn periodic_epochs_due(
+    next_fire: Epoch,
+    current_epoch: Epoch,
+    period: Epoch,
+    coalesce_catchup: bool,
+) -> Vec<Epoch> {
+    if current_epoch < next_fire {
+        return Vec::new();
+    }
+
+    if coalesce_catchup {
+        vec![next_fire]
+    } else {
+        let mut epochs = Vec::new();
+        let mut fire = next_fire;
+        while fire <= current_epoch {
+            epochs.push(fire);
+            fire += period;
+        }
+        epochs
+    }
+}
+
+/// Implementation of the SendableNotification trait for the PeriodicEpochSubscription
+impl<T: Clone + Send> SendableNotification for PeriodicEpochSubscription<T> {
+    /// Function to send notification(s) back to the subscriber for every period boundary
+    /// crossed since the last tick (or a single coalesced notification, if `coalesce_catchup`
+    /// is set and more than one boundary was missed). Stops at the first delivery failure,
+    /// leaving `next_fire` where it is so the same boundary is retried on the next tick
+    fn send_notification(&mut self, current_epoch: Epoch) -> bool {
+        let due = periodic_epochs_due(
+            self.next_fire,
+            current_epoch,
+            self.period,
+            self.coalesce_catchup,
+        );
+
+        let first_due = match due.first() {
+            Some(&epoch) => epoch,
+            None => return true,
         };
+
+        if self.coalesce_catchup {
+            if !self.notify(first_due) {
+                return false;
+            }
+
+            // Fast-forward next_fire past every boundary covered by the coalesced
+            // notification sent above
+            while self.next_fire <= current_epoch {
+                self.next_fire += self.period;
+            }
+        } else {
+            for epoch in due {
+                if !self.notify(epoch) {
+                    return false;
+                }
+                self.next_fire += self.period;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod periodic_epoch_subscription_tests {
+    use super::periodic_epochs_due;
+
+    #[test]
+    fn not_yet_due_returns_nothing() {
+        assert_eq!(periodic_epochs_due(10, 5, 10, false), Vec::new());
+    }
+
+    #[test]
+    fn fires_once_on_time() {
+        assert_eq!(periodic_epochs_due(10, 10, 10, false), vec![10]);
+    }
+
+    #[test]
+    fn replays_every_missed_boundary_when_not_coalescing() {
+        assert_eq!(periodic_epochs_due(10, 35, 10, false), vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn collapses_missed_boundaries_into_one_when_coalescing() {
+        assert_eq!(periodic_epochs_due(10, 35, 10, true), vec![10]);
     }
 }
@@ -3,6 +3,14 @@
 
 use crate::error::Result;
 
+/// A single write operation, as applied by `Storage::write_batch`.
+pub enum WriteOp<Key, Value> {
+    /// Create / update the entry identified by the given key.
+    Put(Key, Value),
+    /// Delete the entry identified by the given key.
+    Delete(Key),
+}
+
 /// This is a generic trait that exposes a very simple key/value CRUD API for data storage.
 /// This trait can be easily implemented for any specific storage backend solution (databases,
 /// volatile memory, flat files, etc.)
@@ -22,4 +30,23 @@ pub trait Storage<ConnData, Key, Value> {
     /// Delete an entry from the storage, identified by its key.
     fn delete(&mut self, key: Key) -> Result<()>;
 
+    /// Iterate, in sorted key order, over every entry whose key starts with `prefix`.
+    fn scan(&self, prefix: Key) -> Result<Box<dyn Iterator<Item = (Key, Value)>>>;
+
+    /// Apply every operation in `ops`, in order, as a single unit.
+    ///
+    /// The default implementation simply applies each operation one at a time, so it is NOT
+    /// atomic. Backends that can offer real atomicity (transactional or ordered stores)
+    /// should override it.
+    fn write_batch(&mut self, ops: Vec<WriteOp<Key, Value>>) -> Result<()> {
+        for op in ops {
+            match op {
+                WriteOp::Put(key, value) => self.put(key, value)?,
+                WriteOp::Delete(key) => self.delete(key)?,
+            }
+        }
+
+        Ok(())
+    }
+
 }
\ No newline at end of file